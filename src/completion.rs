@@ -0,0 +1,184 @@
+use std::fs;
+
+use crate::{list_executables, tokenize, Token, BUILTINS, SYSTEM_PATH};
+
+/// Returns completion candidates for the word under the cursor in `line`.
+///
+/// On the first word of a pipeline stage, candidates are shell builtins and
+/// executables found on `$PATH`. On later words, candidates are filesystem
+/// paths relative to the current directory.
+pub fn complete(line: &str, cursor: usize) -> Vec<String> {
+    let prefix = &line[..cursor];
+    let word_start = word_start(prefix);
+    let word = &prefix[word_start..];
+
+    let mut candidates = if is_first_word(prefix) {
+        complete_command(word)
+    } else {
+        complete_path(word)
+    };
+
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// Returns the byte offset at which the last word of `prefix` starts, i.e.
+/// just past its last whitespace character.
+pub fn word_start(prefix: &str) -> usize {
+    prefix.rfind(char::is_whitespace).map_or(0, |i| i + 1)
+}
+
+/// Whether the word ending at `prefix`'s end is the first word of its
+/// pipeline stage, i.e. nothing but whitespace separates it from the start
+/// of input or from the nearest `|`/`;`/`&&`/`||` before it.
+fn is_first_word(prefix: &str) -> bool {
+    if prefix.trim().is_empty() {
+        return true;
+    }
+
+    match tokenize(prefix) {
+        Ok(tokens) => {
+            // a prefix ending in whitespace or an operator means a new, still
+            // empty word is being typed after the last token
+            let typing_new_word = prefix.ends_with(char::is_whitespace)
+                || !matches!(tokens.last(), Some(Token::Word(_)));
+            let mut seen_word_in_stage = false;
+            let mut last_word_was_first = true;
+
+            for token in tokens {
+                match token {
+                    Token::Pipe | Token::Sequence | Token::And | Token::Or => {
+                        seen_word_in_stage = false;
+                    }
+                    Token::Word(_) => {
+                        last_word_was_first = !seen_word_in_stage;
+                        seen_word_in_stage = true;
+                    }
+                    Token::Redirect(_) => {}
+                }
+            }
+
+            if typing_new_word {
+                !seen_word_in_stage
+            } else {
+                last_word_was_first
+            }
+        }
+        // the prefix ends inside an unterminated quote and can't tokenize;
+        // drop its last whitespace-separated piece and check what comes
+        // before it instead, repeating while that still ends inside the quote
+        Err(_) => is_first_word(&prefix[..word_start(prefix.trim_end())]),
+    }
+}
+
+fn complete_command(word: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = BUILTINS
+        .keys()
+        .map(|name| name.to_string())
+        .filter(|name| name.starts_with(word))
+        .collect();
+
+    candidates.extend(
+        list_executables(&SYSTEM_PATH)
+            .into_iter()
+            .filter(|name| name.starts_with(word)),
+    );
+
+    candidates
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, filename_prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+
+    let read_dir = if dir.is_empty() { "." } else { dir };
+
+    let Ok(entries) = fs::read_dir(read_dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(filename_prefix) {
+                return None;
+            }
+
+            let mut candidate = format!("{dir}{name}");
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                candidate.push('/');
+            }
+
+            Some(candidate)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn word_start_splits_on_the_last_whitespace() {
+        assert_eq!(word_start("echo hi"), 5);
+        assert_eq!(word_start("echo"), 0);
+        assert_eq!(word_start(""), 0);
+    }
+
+    #[test]
+    fn is_first_word_is_true_at_the_start_of_input_or_a_stage() {
+        assert!(is_first_word(""));
+        assert!(is_first_word("ec"));
+        assert!(is_first_word("echo hi | ec"));
+        assert!(is_first_word("echo hi; ec"));
+        assert!(is_first_word("echo hi && ec"));
+        assert!(is_first_word("echo hi || ec"));
+    }
+
+    #[test]
+    fn is_first_word_is_false_for_a_later_word_in_the_same_stage() {
+        assert!(!is_first_word("echo hi"));
+        assert!(!is_first_word("echo hi | grep h"));
+    }
+
+    #[test]
+    fn complete_on_the_first_word_suggests_builtins() {
+        let candidates = complete("ec", 2);
+        assert!(candidates.contains(&"echo".to_owned()));
+    }
+
+    #[test]
+    fn complete_on_a_later_word_after_a_pipe_still_suggests_builtins() {
+        let candidates = complete("echo hi | ec", 12);
+        assert!(candidates.contains(&"echo".to_owned()));
+    }
+
+    #[test]
+    fn complete_on_a_later_word_suggests_filesystem_paths() {
+        let dir = env::temp_dir().join(format!(
+            "shell-completion-test-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("alpha.txt"), b"").unwrap();
+        fs::write(dir.join("alphabet.txt"), b"").unwrap();
+        fs::write(dir.join("beta.txt"), b"").unwrap();
+
+        let line = format!("cat {}/alpha", dir.display());
+        let candidates = complete(&line, line.len());
+
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates
+            .iter()
+            .all(|candidate| candidate.starts_with(&format!("{}/alpha", dir.display()))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::DEFAULT_HISTORY_LIMIT;
+
+const RC_FILE_NAME: &str = ".shellrc";
+const DEFAULT_PROMPT: &str = "$ ";
+
+/// Shell configuration: seeded from defaults, then overridden by the rc file
+/// (`$HOME/.shellrc`) at startup, and by the `set` builtin at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub prompt: String,
+    /// `None` means history tracking is turned off entirely.
+    pub history_limit: Option<usize>,
+    /// Whether to print the full `anyhow` error chain on failure, rather
+    /// than just its top-level message.
+    pub show_errors: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prompt: DEFAULT_PROMPT.to_owned(),
+            history_limit: Some(DEFAULT_HISTORY_LIMIT),
+            show_errors: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the default configuration and applies `$HOME/.shellrc` on top
+    /// of it, if the file exists. A malformed line is reported and skipped
+    /// rather than aborting startup.
+    pub fn load() -> Self {
+        let mut config = Self::default();
+
+        let Some(path) = rc_file_path() else {
+            return config;
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return config;
+        };
+
+        for line in contents.lines() {
+            if let Err(err) = config.apply_line(line) {
+                eprintln!("{}: {err}", path.display());
+            }
+        }
+
+        config
+    }
+
+    fn apply_line(&mut self, line: &str) -> Result<()> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let (key, value) = line
+            .split_once(':')
+            .context("expected a 'key: value' line")?;
+
+        self.set(key.trim(), value.trim())
+    }
+
+    /// Sets a single config key from its string form, shared by the rc file
+    /// parser and the `set` builtin.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "prompt" => self.prompt = value.to_owned(),
+            "history-limit" => {
+                self.history_limit = match value {
+                    "off" => None,
+                    limit => Some(
+                        limit
+                            .parse()
+                            .context("history-limit must be a number or 'off'")?,
+                    ),
+                }
+            }
+            "show-errors" => {
+                self.show_errors = value
+                    .parse()
+                    .context("show-errors must be 'true' or 'false'")?;
+            }
+            _ => bail!("unknown config key '{key}'"),
+        }
+
+        Ok(())
+    }
+
+    /// Reads a single config key back out in its string form, or `None` if
+    /// `key` isn't a known config key.
+    pub fn get(&self, key: &str) -> Option<String> {
+        Some(match key {
+            "prompt" => self.prompt.clone(),
+            "history-limit" => match self.history_limit {
+                Some(limit) => limit.to_string(),
+                None => "off".to_owned(),
+            },
+            "show-errors" => self.show_errors.to_string(),
+            _ => return None,
+        })
+    }
+
+    /// The keys understood by [`Config::get`]/[`Config::set`], in display order.
+    pub const KEYS: [&'static str; 3] = ["prompt", "history-limit", "show-errors"];
+}
+
+fn rc_file_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(RC_FILE_NAME))
+}
@@ -0,0 +1,59 @@
+use crate::{VarStore, Word, WordPart};
+
+/// Expands `$NAME` / `${NAME}` references found in a word's expandable
+/// segments, leaving its single-quoted (literal) segments untouched.
+pub fn expand_word(word: &Word, vars: &VarStore) -> String {
+    let mut output = String::new();
+
+    for part in &word.0 {
+        match part {
+            WordPart::Literal(text) => output.push_str(text),
+            WordPart::Expandable(text) => expand_into(text, vars, &mut output),
+        }
+    }
+
+    output
+}
+
+fn expand_into(text: &str, vars: &VarStore, output: &mut String) {
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            output.push(ch);
+            continue;
+        }
+
+        match chars.peek().copied() {
+            Some('{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                push_value(output, vars, &name);
+            }
+            Some('?') => {
+                chars.next();
+                push_value(output, vars, "?");
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        name.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                push_value(output, vars, &name);
+            }
+            // a lone `$` with nothing expansion-worthy after it is kept as-is
+            _ => output.push('$'),
+        }
+    }
+}
+
+fn push_value(output: &mut String, vars: &VarStore, name: &str) {
+    if let Some(value) = vars.get(name) {
+        output.push_str(value);
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    mem,
+    os::fd::AsRawFd,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+use crate::completion::word_start;
+
+const HISTORY_FILE_NAME: &str = ".shell_history";
+
+/// A raw-mode line reader with persistent history and basic cursor editing.
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+/// What the user did with a line.
+pub enum ReadOutcome {
+    /// A line was accepted (possibly empty).
+    Line(String),
+    /// Ctrl-D was pressed on an empty line; the shell should exit.
+    Eof,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        let history_path = history_file_path();
+        let history = history_path
+            .as_deref()
+            .map(load_history)
+            .unwrap_or_default();
+
+        debug!(history_len = history.len(), ?history_path, "loaded history");
+
+        Self {
+            history,
+            history_path,
+        }
+    }
+
+    /// Reads a single line from the terminal, with left/right cursor movement,
+    /// backspace, Ctrl-C to abort the current line, up/down to walk history,
+    /// and Tab to complete the word under the cursor via `complete`.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        history_limit: Option<usize>,
+        complete: impl Fn(&str, usize) -> Vec<String>,
+    ) -> Result<ReadOutcome> {
+        let _raw_mode = RawMode::enable().context("unable to enter raw mode")?;
+
+        let mut stdout = io::stdout();
+        let mut buffer: Vec<char> = Vec::new();
+        let mut cursor = 0usize;
+        let mut history_cursor: Option<usize> = None;
+        let mut draft = String::new();
+
+        render(&mut stdout, prompt, &buffer, cursor)?;
+
+        loop {
+            let byte = read_byte()?;
+
+            match byte {
+                // Ctrl-C: abort the current line
+                0x03 => {
+                    write!(stdout, "^C\r\n")?;
+                    stdout.flush()?;
+                    return Ok(ReadOutcome::Line(String::new()));
+                }
+                // Ctrl-D: exit on an empty line, otherwise ignore
+                0x04 if buffer.is_empty() => {
+                    write!(stdout, "\r\n")?;
+                    stdout.flush()?;
+                    return Ok(ReadOutcome::Eof);
+                }
+                // Enter
+                b'\r' | b'\n' => {
+                    write!(stdout, "\r\n")?;
+                    stdout.flush()?;
+                    let line: String = buffer.into_iter().collect();
+                    self.record(&line, history_limit)?;
+                    return Ok(ReadOutcome::Line(line));
+                }
+                // Backspace
+                0x7f | 0x08 if cursor > 0 => {
+                    cursor -= 1;
+                    buffer.remove(cursor);
+                }
+                // Tab: delegate to the completer
+                b'\t' => {
+                    let line: String = buffer.iter().collect();
+                    let candidates = complete(&line, cursor);
+
+                    match candidates.as_slice() {
+                        [] => {}
+                        [only] => {
+                            let word_start = word_start(&line[..cursor]);
+                            let typed = &line[word_start..cursor];
+                            for ch in only[typed.len()..].chars() {
+                                buffer.insert(cursor, ch);
+                                cursor += 1;
+                            }
+                        }
+                        many => {
+                            write!(stdout, "\r\n{}\r\n", many.join("  "))?;
+                        }
+                    }
+                }
+                // Escape sequences (arrow keys)
+                0x1b => {
+                    if read_byte()? != b'[' {
+                        continue;
+                    }
+
+                    match read_byte()? {
+                        b'C' if cursor < buffer.len() => cursor += 1,
+                        b'D' if cursor > 0 => cursor -= 1,
+                        b'A' if !self.history.is_empty() => {
+                            let next = match history_cursor {
+                                None => {
+                                    draft = buffer.iter().collect();
+                                    self.history.len() - 1
+                                }
+                                Some(0) => 0,
+                                Some(i) => i - 1,
+                            };
+                            history_cursor = Some(next);
+                            buffer = self.history[next].chars().collect();
+                            cursor = buffer.len();
+                        }
+                        b'B' => match history_cursor {
+                            Some(i) if i + 1 < self.history.len() => {
+                                history_cursor = Some(i + 1);
+                                buffer = self.history[i + 1].chars().collect();
+                                cursor = buffer.len();
+                            }
+                            Some(_) => {
+                                history_cursor = None;
+                                buffer = draft.chars().collect();
+                                cursor = buffer.len();
+                            }
+                            None => {}
+                        },
+                        _ => {}
+                    }
+                }
+                ch if ch.is_ascii() && !ch.is_ascii_control() => {
+                    buffer.insert(cursor, ch as char);
+                    cursor += 1;
+                }
+                _ => {}
+            }
+
+            render(&mut stdout, prompt, &buffer, cursor)?;
+        }
+    }
+
+    fn record(&mut self, line: &str, history_limit: Option<usize>) -> Result<()> {
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let Some(history_limit) = history_limit else {
+            return Ok(());
+        };
+
+        self.history.push(line.to_owned());
+        if self.history.len() > history_limit {
+            let overflow = self.history.len() - history_limit;
+            self.history.drain(0..overflow);
+        }
+
+        if let Some(path) = &self.history_path {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("unable to open history file '{}'", path.display()))?;
+            writeln!(file, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn render(writer: &mut impl Write, prompt: &str, buffer: &[char], cursor: usize) -> Result<()> {
+    let line: String = buffer.iter().collect();
+    write!(writer, "\r\x1b[K{prompt}{line}")?;
+
+    let trailing = buffer.len() - cursor;
+    if trailing > 0 {
+        write!(writer, "\x1b[{trailing}D")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_byte() -> Result<u8> {
+    let mut byte = [0u8; 1];
+    io::stdin().lock().read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn history_file_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME))
+}
+
+fn load_history(path: &std::path::Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|contents| contents.lines().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Puts the terminal into raw mode for its lifetime, restoring the original
+/// settings on drop.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        let fd = io::stdin().as_raw_fd();
+
+        let original = unsafe {
+            let mut termios = mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            termios
+        };
+
+        let mut raw = original;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let fd = io::stdin().as_raw_fd();
+        unsafe {
+            libc::tcsetattr(fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
@@ -1,22 +1,38 @@
 use std::{
     collections::HashMap,
     env::{self, consts::EXE_SUFFIX},
-    fs,
+    fs::{self, File, OpenOptions},
     io::{self, Write},
     path::PathBuf,
-    process::{Command, ExitCode},
+    process::{Command, ExitCode, Stdio},
     sync::LazyLock,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use tracing::{debug, instrument, trace};
 
+use config::Config;
+use expansion::expand_word;
+use line_editor::{LineEditor, ReadOutcome};
+
+mod completion;
+mod config;
+mod expansion;
+mod line_editor;
+
+const DEFAULT_HISTORY_LIMIT: usize = 1000;
+
 #[cfg(windows)]
 const SYSTEM_PATH_SPERATOR: &str = ";";
 #[cfg(not(windows))]
 const SYSTEM_PATH_SPERATOR: &str = ":";
 
-type BuiltinFn = fn(Vec<String>) -> Result<Option<ExitCode>>;
+/// The shell's variable store, seeded from the process environment and kept
+/// up to date with `$?`.
+type VarStore = HashMap<String, String>;
+
+type BuiltinFn =
+    fn(Vec<String>, &mut dyn Write, &mut VarStore, &mut Config) -> Result<Option<ExitCode>>;
 
 static SYSTEM_PATH: LazyLock<String> = LazyLock::new(|| env::var("PATH").unwrap_or_default());
 static BUILTINS: LazyLock<HashMap<&str, BuiltinFn>> = LazyLock::new(|| {
@@ -26,25 +42,34 @@ static BUILTINS: LazyLock<HashMap<&str, BuiltinFn>> = LazyLock::new(|| {
         ("type", builtin_type as BuiltinFn),
         ("pwd", builtin_pwd as BuiltinFn),
         ("cd", builtin_cd as BuiltinFn),
+        ("set", builtin_set as BuiltinFn),
     ])
 });
 
 fn main() -> Result<ExitCode> {
-    let prompt = "$ ";
-
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
     debug!(?SYSTEM_PATH);
 
-    loop {
-        print!("{prompt}");
-        io::stdout().flush()?;
+    let mut config = Config::load();
+    debug!(?config);
+
+    let mut vars: VarStore = env::vars().collect();
+    vars.insert("?".to_owned(), "0".to_owned());
+
+    let mut line_editor = LineEditor::new();
 
-        // Wait for user input
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+    loop {
+        let input = match line_editor.read_line(
+            &config.prompt,
+            config.history_limit,
+            completion::complete,
+        )? {
+            ReadOutcome::Line(line) => line,
+            ReadOutcome::Eof => return Ok(ExitCode::SUCCESS),
+        };
         debug!(input);
 
         let input = input.trim_start().trim_newline();
@@ -53,190 +78,734 @@ fn main() -> Result<ExitCode> {
             continue;
         }
 
-        let cmd = parse(input).context("unable to parse prompt")?;
-        debug!(?cmd);
+        let plan = match parse(input).context("unable to parse prompt") {
+            Ok(plan) => plan,
+            Err(err) => {
+                print_error(&err, config.show_errors);
+                continue;
+            }
+        };
+        debug!(?plan);
+
+        for element in plan {
+            let last_status = vars.get("?").and_then(|s| s.parse::<u8>().ok()).unwrap_or(0);
+            let should_run = match element.separator {
+                None | Some(Separator::Sequence) => true,
+                Some(Separator::And) => last_status == 0,
+                Some(Separator::Or) => last_status != 0,
+            };
 
-        let program = cmd.program.as_str();
-        if let Some(builtin_fn) = BUILTINS.get(program) {
-            let exit_code = builtin_fn(cmd.args)?;
-            if let Some(exit_code) = exit_code {
-                return Ok(exit_code);
+            if !should_run {
+                continue;
             }
-        } else if find_executable(&SYSTEM_PATH, program).is_some() {
-            debug!(program, "executing program");
-            let output = Command::new(program).args(cmd.args).output().unwrap();
 
-            io::stdout().write_all(&output.stdout).unwrap();
-            io::stderr().write_all(&output.stderr).unwrap();
-        } else {
-            println!("{program}: command not found");
+            let outcome = match run_pipeline(&element.pipeline, &mut vars, &mut config) {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    print_error(&err, config.show_errors);
+                    vars.insert("?".to_owned(), "1".to_owned());
+                    continue;
+                }
+            };
+
+            match outcome {
+                PipelineOutcome::Continue(status) => {
+                    vars.insert("?".to_owned(), status.to_string());
+                }
+                PipelineOutcome::Exit(exit_code) => return Ok(exit_code),
+            }
         }
     }
 }
 
+/// Prints an error the way `config.show_errors` asks for: the full `anyhow`
+/// cause chain, or just its top-level message.
+fn print_error(err: &anyhow::Error, show_errors: bool) {
+    if show_errors {
+        eprintln!("{err:#}");
+    } else {
+        eprintln!("{err}");
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Cmd {
-    program: String,
-    args: Vec<String>,
+    /// Leading `NAME=value` assignments, in order.
+    assignments: Vec<(String, Word)>,
+    /// The program to run, or `None` if the stage was assignments only.
+    program: Option<Word>,
+    args: Vec<Word>,
+    redirections: Vec<Redirection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RedirectMode {
+    Read,
+    Truncate,
+    Append,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Redirection {
+    fd: i32,
+    mode: RedirectMode,
+    target: Word,
+}
+
+/// A single segment of a word: either literal text from a single-quoted run
+/// or a backslash escape (never expanded), or expandable text from an
+/// unquoted/double-quoted run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WordPart {
+    Literal(String),
+    Expandable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Word(Vec<WordPart>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Separator {
+    Sequence,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SequenceElement {
+    separator: Option<Separator>,
+    pipeline: Vec<Cmd>,
+}
+
+type Plan = Vec<SequenceElement>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(Word),
+    Pipe,
+    Sequence,
+    And,
+    Or,
+    Redirect(RedirectSpec),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RedirectSpec {
+    fd: i32,
+    mode: RedirectMode,
 }
 
 #[instrument]
-fn parse(input: &str) -> Result<Cmd> {
+fn parse(input: &str) -> Result<Plan> {
     assert!(!input.is_empty());
 
-    let mut components = Vec::new();
+    let mut tokens = tokenize(input)?.into_iter().peekable();
 
-    let mut current_component = String::new();
-    let mut it = input.char_indices().peekable();
-    while let Some((i, ch)) = it.next() {
-        if ch == '\'' {
-            let (closing_quote_index, _) = it
-                .by_ref()
-                .find(|(_, ch)| *ch == '\'')
-                .context("did not find a closing single quote")?;
-
-            components.push(input[i + 1..closing_quote_index].to_owned());
-        } else if ch == '"' {
-            let (closing_quote_index, _) = it
-                .by_ref()
-                .find(|(_, ch)| *ch == '"')
-                .context("did not find a closing double quote")?;
-
-            components.push(input[i + 1..closing_quote_index].to_owned());
-        } else if ch == '\\' {
-            let (_, next_char) = it
-                .next()
-                .context("expected a character after '\', but got nothing")?;
-
-            current_component.push(next_char);
-        } else if ch.is_whitespace() {
-            components.push(current_component);
-            current_component = String::new();
-            // ignore all following whitespace
-            while let Some((_, search_char)) = it.peek() {
-                if !search_char.is_whitespace() {
-                    break;
-                }
+    let mut plan = Vec::new();
+    let mut separator = None;
+    let mut pipeline = Vec::new();
+    let mut stage_words = Vec::new();
+    let mut stage_redirections = Vec::new();
 
-                it.next();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => stage_words.push(word),
+            Token::Redirect(spec) => {
+                let target = match tokens.next() {
+                    Some(Token::Word(word)) => word,
+                    _ => bail!("expected a target path after a redirection operator"),
+                };
+                stage_redirections.push(Redirection {
+                    fd: spec.fd,
+                    mode: spec.mode,
+                    target,
+                });
+            }
+            Token::Pipe => {
+                pipeline.push(cmd_from_stage(&mut stage_words, &mut stage_redirections)?)
+            }
+            Token::Sequence | Token::And | Token::Or => {
+                pipeline.push(cmd_from_stage(&mut stage_words, &mut stage_redirections)?);
+                plan.push(SequenceElement {
+                    separator,
+                    pipeline: std::mem::take(&mut pipeline),
+                });
+                separator = Some(match token {
+                    Token::Sequence => Separator::Sequence,
+                    Token::And => Separator::And,
+                    Token::Or => Separator::Or,
+                    Token::Word(_) | Token::Pipe | Token::Redirect(_) => unreachable!(),
+                });
             }
-        } else {
-            current_component.push(ch);
         }
     }
 
-    if !current_component.is_empty() {
-        components.push(current_component);
+    if !stage_words.is_empty() || !stage_redirections.is_empty() || !pipeline.is_empty() {
+        pipeline.push(cmd_from_stage(&mut stage_words, &mut stage_redirections)?);
+        plan.push(SequenceElement { separator, pipeline });
+    }
+
+    Ok(plan)
+}
+
+fn cmd_from_stage(words: &mut Vec<Word>, redirections: &mut Vec<Redirection>) -> Result<Cmd> {
+    if words.is_empty() {
+        bail!("expected a command before the separator");
     }
 
-    let program = components.remove(0);
+    let mut words = std::mem::take(words).into_iter().peekable();
+
+    let mut assignments = Vec::new();
+    while let Some(word) = words.peek() {
+        let Some(assignment) = split_assignment(word) else {
+            break;
+        };
+        assignments.push(assignment);
+        words.next();
+    }
+
+    let program = words.next();
+    let args = words.collect();
 
     Ok(Cmd {
+        assignments,
         program,
-        args: components,
+        args,
+        redirections: std::mem::take(redirections),
     })
 }
 
-fn find_executable(search_path: &str, executable_name: &str) -> Option<PathBuf> {
-    debug!(executable_name, "searching for executable");
+/// If `word` starts with a `NAME=` assignment in an expandable segment,
+/// splits it into the variable name and the remaining word (the value).
+/// Expandable segments come from unquoted and double-quoted text alike, so a
+/// double-quoted `"NAME=value"` is split too; a single-quoted or
+/// backslash-escaped `NAME=` is not.
+fn split_assignment(word: &Word) -> Option<(String, Word)> {
+    let Some(WordPart::Expandable(first)) = word.0.first() else {
+        return None;
+    };
 
-    let executable_name_with_suffix = format!("{executable_name}{EXE_SUFFIX}");
+    let eq_index = first.find('=')?;
+    let name = &first[..eq_index];
+    if name.is_empty() || !is_identifier(name) {
+        return None;
+    }
 
-    for path in search_path.split(SYSTEM_PATH_SPERATOR) {
-        trace!(
-            executable_name,
-            executable_name_with_suffix,
-            path,
-            "searching for executable"
-        );
-        let Ok(entries) = fs::read_dir(path) else {
+    let mut value_parts = Vec::with_capacity(word.0.len());
+    let rest = &first[eq_index + 1..];
+    if !rest.is_empty() {
+        value_parts.push(WordPart::Expandable(rest.to_owned()));
+    }
+    value_parts.extend(word.0[1..].iter().cloned());
+
+    Some((name.to_owned(), Word(value_parts)))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuoteState {
+    Unquoted,
+    InSingle,
+    InDouble,
+}
+
+/// Accumulates the segments of a single word as the tokenizer walks the
+/// input, keeping literal (single-quoted or backslash-escaped) and expandable
+/// runs distinct so a
+/// later pass can expand `$NAME` without touching quoted text.
+#[derive(Default)]
+struct WordBuilder {
+    parts: Vec<WordPart>,
+    segment: String,
+    segment_is_literal: bool,
+    has_token: bool,
+    /// Whether any part of the word typed so far came from inside a quote,
+    /// so callers can tell a bare digit run (e.g. the `2` in `2>out`) apart
+    /// from a quoted one (e.g. `'2'>out`, where `2` stays an ordinary word
+    /// and `>` redirects stdout).
+    quoted: bool,
+}
+
+impl WordBuilder {
+    fn push(&mut self, ch: char, is_literal: bool) {
+        if self.segment_is_literal != is_literal {
+            self.flush_segment();
+            self.segment_is_literal = is_literal;
+        }
+        self.segment.push(ch);
+        self.has_token = true;
+    }
+
+    /// Like [`Self::push`], but for a character read while inside a quote.
+    fn push_quoted(&mut self, ch: char, is_literal: bool) {
+        self.quoted = true;
+        self.push(ch, is_literal);
+    }
+
+    /// Marks that a token was started without necessarily pushing a char,
+    /// so that e.g. `echo ''` still produces one empty argument. Only used
+    /// for opening an (possibly empty) quote, so it also marks the word as
+    /// quoted.
+    fn mark_started(&mut self) {
+        self.has_token = true;
+        self.quoted = true;
+    }
+
+    fn flush_segment(&mut self) {
+        if self.segment.is_empty() {
+            return;
+        }
+
+        let segment = std::mem::take(&mut self.segment);
+        self.parts.push(if self.segment_is_literal {
+            WordPart::Literal(segment)
+        } else {
+            WordPart::Expandable(segment)
+        });
+    }
+
+    fn take(&mut self) -> Option<Word> {
+        self.flush_segment();
+        self.quoted = false;
+        if !self.has_token {
+            return None;
+        }
+
+        self.has_token = false;
+        Some(Word(std::mem::take(&mut self.parts)))
+    }
+}
+
+#[instrument]
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    assert!(!input.is_empty());
+
+    let mut tokens = Vec::new();
+    let mut word = WordBuilder::default();
+    let mut state = QuoteState::Unquoted;
+
+    let mut it = input.char_indices().peekable();
+    while let Some((_, ch)) = it.next() {
+        match state {
+            QuoteState::InSingle => {
+                if ch == '\'' {
+                    state = QuoteState::Unquoted;
+                } else {
+                    word.push_quoted(ch, true);
+                }
+            }
+            QuoteState::InDouble => {
+                if ch == '"' {
+                    state = QuoteState::Unquoted;
+                } else if ch == '\\' {
+                    // double quotes only escape a handful of characters;
+                    // anywhere else the backslash is kept literally
+                    match it.peek().map(|(_, c)| *c) {
+                        Some(next @ ('$' | '`' | '"' | '\\')) => {
+                            word.push_quoted(next, true);
+                            it.next();
+                        }
+                        Some('\n') => {
+                            it.next();
+                        }
+                        _ => word.push_quoted('\\', false),
+                    }
+                } else {
+                    word.push_quoted(ch, false);
+                }
+            }
+            QuoteState::Unquoted => {
+                if ch == '\'' {
+                    state = QuoteState::InSingle;
+                    word.mark_started();
+                } else if ch == '"' {
+                    state = QuoteState::InDouble;
+                    word.mark_started();
+                } else if ch == '\\' {
+                    let (_, next_char) = it
+                        .next()
+                        .context("expected a character after '\', but got nothing")?;
+
+                    word.push(next_char, true);
+                } else if ch.is_whitespace() {
+                    flush_word(&mut word, &mut tokens);
+                    // ignore all following whitespace
+                    while let Some((_, search_char)) = it.peek() {
+                        if !search_char.is_whitespace() {
+                            break;
+                        }
+
+                        it.next();
+                    }
+                } else if ch == '|' && it.peek().map(|(_, c)| *c) == Some('|') {
+                    it.next();
+                    flush_word(&mut word, &mut tokens);
+                    tokens.push(Token::Or);
+                } else if ch == '&' && it.peek().map(|(_, c)| *c) == Some('&') {
+                    it.next();
+                    flush_word(&mut word, &mut tokens);
+                    tokens.push(Token::And);
+                } else if ch == '|' {
+                    flush_word(&mut word, &mut tokens);
+                    tokens.push(Token::Pipe);
+                } else if ch == ';' {
+                    flush_word(&mut word, &mut tokens);
+                    tokens.push(Token::Sequence);
+                } else if ch == '>' || ch == '<' {
+                    // a bare (unquoted) digit run immediately before the
+                    // operator selects the fd (e.g. `2>`); a quoted one
+                    // (e.g. `'2'>`) stays an ordinary word like any other,
+                    // and the redirect applies to stdout
+                    let fd = if word.has_token
+                        && !word.quoted
+                        && word.segment.chars().all(|c| c.is_ascii_digit())
+                        && word.parts.is_empty()
+                    {
+                        let fd = word.segment.parse().context("fd is too large")?;
+                        word.segment.clear();
+                        word.has_token = false;
+                        fd
+                    } else {
+                        flush_word(&mut word, &mut tokens);
+                        if ch == '>' { 1 } else { 0 }
+                    };
+
+                    let mode = if ch == '>' {
+                        if it.peek().map(|(_, c)| *c) == Some('>') {
+                            it.next();
+                            RedirectMode::Append
+                        } else {
+                            RedirectMode::Truncate
+                        }
+                    } else {
+                        RedirectMode::Read
+                    };
+
+                    tokens.push(Token::Redirect(RedirectSpec { fd, mode }));
+                } else {
+                    word.push(ch, false);
+                }
+            }
+        }
+    }
+
+    match state {
+        QuoteState::InSingle => bail!("did not find a closing single quote"),
+        QuoteState::InDouble => bail!("did not find a closing double quote"),
+        QuoteState::Unquoted => {}
+    }
+
+    flush_word(&mut word, &mut tokens);
+
+    Ok(tokens)
+}
+
+fn flush_word(word: &mut WordBuilder, tokens: &mut Vec<Token>) {
+    if let Some(word) = word.take() {
+        tokens.push(Token::Word(word));
+    }
+}
+
+enum PipelineOutcome {
+    Continue(u8),
+    Exit(ExitCode),
+}
+
+#[instrument(skip(vars, config))]
+fn run_pipeline(pipeline: &[Cmd], vars: &mut VarStore, config: &mut Config) -> Result<PipelineOutcome> {
+    let last_index = pipeline.len() - 1;
+
+    let mut children = Vec::new();
+    let mut pending_stdin = None;
+    let mut prev_stdout = None;
+    let mut last_status = 0u8;
+    let mut shell_exit = None;
+    // whether the pipeline's last stage is an external process, so the
+    // children-wait loop below knows whether its exit code is the one that
+    // should become the pipeline's status (as opposed to a builtin's or a
+    // "command not found"'s, which set `last_status` directly)
+    let mut last_stage_is_external = false;
+
+    for (i, cmd) in pipeline.iter().enumerate() {
+        let is_last = i == last_index;
+        last_stage_is_external = false;
+
+        // a stage with no program is a bare `NAME=value` assignment; it
+        // updates the shell's own variables rather than running anything
+        let Some(program_word) = &cmd.program else {
+            for (name, value) in &cmd.assignments {
+                let value = expand_word(value, vars);
+                vars.insert(name.clone(), value);
+            }
+            last_status = 0;
+            prev_stdout = None;
+            pending_stdin = None;
             continue;
         };
 
-        for entry in entries {
-            let entry = entry.unwrap();
-            if entry.file_name() == executable_name
-                || entry.file_name() == executable_name_with_suffix.as_str()
+        // assignments on a command line only apply to that command, so they
+        // go into a throwaway overlay rather than the shell's own store
+        let mut local_vars = vars.clone();
+        for (name, value) in &cmd.assignments {
+            let value = expand_word(value, vars);
+            local_vars.insert(name.clone(), value);
+        }
+
+        let program = expand_word(program_word, &local_vars);
+        let args: Vec<String> = cmd
+            .args
+            .iter()
+            .map(|arg| expand_word(arg, &local_vars))
+            .collect();
+        let mut files = open_redirections(&cmd.redirections, &local_vars)?;
+
+        if let Some(builtin_fn) = BUILTINS.get(program.as_str()) {
+            prev_stdout = None;
+            pending_stdin = None;
+
+            let exit_code = if let Some(mut file) = files.remove(&1) {
+                builtin_fn(args, &mut file, &mut local_vars, config)?
+            } else if is_last {
+                let mut stdout = io::stdout();
+                builtin_fn(args, &mut stdout, &mut local_vars, config)?
+            } else {
+                let mut buffer = Vec::new();
+                let exit_code = builtin_fn(args, &mut buffer, &mut local_vars, config)?;
+                pending_stdin = Some(buffer);
+                exit_code
+            };
+
+            match exit_code {
+                Some(exit_code) => shell_exit = Some(exit_code),
+                None => last_status = 0,
+            }
+        } else if find_executable(&SYSTEM_PATH, &program).is_some() {
+            debug!(program, "executing program");
+
+            let mut command = Command::new(&program);
+            command.args(&args);
+            command.envs(&local_vars);
+
+            if let Some(file) = files.remove(&0) {
+                command.stdin(Stdio::from(file));
+            } else if let Some(stdout) = prev_stdout.take() {
+                command.stdin(Stdio::from(stdout));
+            } else if pending_stdin.is_some() {
+                command.stdin(Stdio::piped());
+            } else {
+                command.stdin(Stdio::inherit());
+            }
+
+            if let Some(file) = files.remove(&1) {
+                command.stdout(Stdio::from(file));
+            } else if is_last {
+                command.stdout(Stdio::inherit());
+            } else {
+                command.stdout(Stdio::piped());
+            }
+
+            if let Some(file) = files.remove(&2) {
+                command.stderr(Stdio::from(file));
+            } else {
+                command.stderr(Stdio::inherit());
+            }
+
+            let mut child = command
+                .spawn()
+                .with_context(|| format!("unable to run '{program}'"))?;
+            prev_stdout = child.stdout.take();
+
+            let write_result = if let Some(bytes) = pending_stdin.take()
+                && let Some(mut stdin) = child.stdin.take()
             {
-                return Some(entry.path());
+                stdin.write_all(&bytes)
+            } else {
+                Ok(())
+            };
+
+            children.push(child);
+            write_result?;
+            last_stage_is_external = is_last;
+        } else {
+            let message = format!("{program}: command not found");
+            match files.remove(&2) {
+                Some(mut file) => writeln!(file, "{message}")?,
+                None => eprintln!("{message}"),
             }
+            last_status = 127;
+            prev_stdout = None;
+            pending_stdin = None;
+        }
+    }
+
+    for mut child in children {
+        let status = child.wait().context("unable to wait for a pipeline stage")?;
+        if last_stage_is_external {
+            last_status = status.code().unwrap_or(1) as u8;
         }
     }
 
-    None
+    Ok(match shell_exit {
+        Some(exit_code) => PipelineOutcome::Exit(exit_code),
+        None => PipelineOutcome::Continue(last_status),
+    })
 }
 
-#[instrument]
-fn builtin_exit(args: Vec<String>) -> Result<Option<ExitCode>> {
-    let Some(exit_code) = args.first() else {
-        return Ok(Some(ExitCode::SUCCESS));
-    };
+fn open_redirections(redirections: &[Redirection], vars: &VarStore) -> Result<HashMap<i32, File>> {
+    let mut files = HashMap::new();
+
+    for redirection in redirections {
+        let target = expand_word(&redirection.target, vars);
+        let file = match redirection.mode {
+            RedirectMode::Read => File::open(&target)
+                .with_context(|| format!("unable to open '{target}' for reading"))?,
+            RedirectMode::Truncate => File::create(&target)
+                .with_context(|| format!("unable to open '{target}' for writing"))?,
+            RedirectMode::Append => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&target)
+                .with_context(|| format!("unable to open '{target}' for appending"))?,
+        };
+
+        files.insert(redirection.fd, file);
+    }
+
+    Ok(files)
+}
+
+fn find_executable(search_path: &str, executable_name: &str) -> Option<PathBuf> {
+    debug!(executable_name, "searching for executable");
+
+    let executable_name_with_suffix = format!("{executable_name}{EXE_SUFFIX}");
+
+    path_dir_entries(search_path)
+        .find(|entry| {
+            entry.file_name() == executable_name
+                || entry.file_name() == executable_name_with_suffix.as_str()
+        })
+        .map(|entry| entry.path())
+}
+
+/// Lists the names of every entry found while walking `search_path`, for
+/// completion candidates.
+fn list_executables(search_path: &str) -> Vec<String> {
+    path_dir_entries(search_path)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
 
-    let exit_code = exit_code
-        .parse::<u8>()
-        .context("unable to parse exit code, it must be a number in the range [0,255]")?;
+fn path_dir_entries(search_path: &str) -> impl Iterator<Item = fs::DirEntry> + '_ {
+    search_path.split(SYSTEM_PATH_SPERATOR).flat_map(|path| {
+        trace!(path, "walking PATH directory");
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+    })
+}
+
+#[instrument(skip(writer, _config))]
+fn builtin_exit(
+    args: Vec<String>,
+    writer: &mut dyn Write,
+    vars: &mut VarStore,
+    _config: &mut Config,
+) -> Result<Option<ExitCode>> {
+    let _ = writer;
+
+    let exit_code = match args.first() {
+        Some(exit_code) => exit_code
+            .parse::<u8>()
+            .context("unable to parse exit code, it must be a number in the range [0,255]")?,
+        // with no argument, `exit` reuses the status of the last command
+        None => vars
+            .get("?")
+            .and_then(|status| status.parse::<u8>().ok())
+            .unwrap_or(0),
+    };
 
     Ok(Some(exit_code.into()))
 }
 
-#[instrument]
-fn builtin_echo(args: Vec<String>) -> Result<Option<ExitCode>> {
+#[instrument(skip(writer, _vars, _config))]
+fn builtin_echo(
+    args: Vec<String>,
+    writer: &mut dyn Write,
+    _vars: &mut VarStore,
+    _config: &mut Config,
+) -> Result<Option<ExitCode>> {
     debug!("executable builtin command 'echo'");
     let output = args.join(" ");
-    println!("{output}");
+    writeln!(writer, "{output}")?;
     Ok(None)
 }
 
-#[instrument]
-fn builtin_type(args: Vec<String>) -> Result<Option<ExitCode>> {
+#[instrument(skip(writer, _vars, _config))]
+fn builtin_type(
+    args: Vec<String>,
+    writer: &mut dyn Write,
+    _vars: &mut VarStore,
+    _config: &mut Config,
+) -> Result<Option<ExitCode>> {
     debug!("executable builtin command 'type'");
 
     for arg in args {
         if BUILTINS.contains_key(&arg.as_str()) {
-            println!("{arg} is a shell builtin")
+            writeln!(writer, "{arg} is a shell builtin")?;
         } else if let Some(executable_path) = find_executable(&SYSTEM_PATH, &arg) {
-            println!("{arg} is {}", executable_path.display());
+            writeln!(writer, "{arg} is {}", executable_path.display())?;
         } else {
-            println!("{arg}: not found");
+            writeln!(writer, "{arg}: not found")?;
         }
     }
 
     Ok(None)
 }
 
-#[instrument]
-fn builtin_pwd(_args: Vec<String>) -> Result<Option<ExitCode>> {
+#[instrument(skip(writer, _vars, _config))]
+fn builtin_pwd(
+    _args: Vec<String>,
+    writer: &mut dyn Write,
+    _vars: &mut VarStore,
+    _config: &mut Config,
+) -> Result<Option<ExitCode>> {
     debug!("executable builtin command 'pwd'");
 
-    println!("{}", env::current_dir()?.display());
+    writeln!(writer, "{}", env::current_dir()?.display())?;
 
     Ok(None)
 }
 
-#[instrument]
-fn builtin_cd(args: Vec<String>) -> Result<Option<ExitCode>> {
-    assert!(args.len() == 1);
-
+#[instrument(skip(writer, vars, _config))]
+fn builtin_cd(
+    args: Vec<String>,
+    writer: &mut dyn Write,
+    vars: &mut VarStore,
+    _config: &mut Config,
+) -> Result<Option<ExitCode>> {
     debug!("executable builtin command 'cd'");
 
-    let input = args
-        .into_iter()
-        .next()
-        .expect("expected path to be passed to 'cd' command");
-
-    if input == "~" {
-        let home = env::var("HOME")?;
-        debug!(home, "changing to home directory");
-
-        env::set_current_dir(home)?;
-        return Ok(None);
-    }
+    // with no argument, or with `~`, `cd` goes to the home directory
+    let input = match args.into_iter().next() {
+        Some(input) if input != "~" => input,
+        _ => vars
+            .get("HOME")
+            .cloned()
+            .or_else(|| env::var("HOME").ok())
+            .context("HOME is not set")?,
+    };
 
     let cwd = env::current_dir()?;
     let input_path = match fs::canonicalize(&input) {
         Ok(path) => path,
         Err(err) if err.kind() == io::ErrorKind::NotFound => {
-            println!("cd: {}: No such file or directory", input);
+            writeln!(writer, "cd: {}: No such file or directory", input)?;
             return Ok(None);
         }
         Err(err) => return Err(err.into()),
@@ -249,6 +818,33 @@ fn builtin_cd(args: Vec<String>) -> Result<Option<ExitCode>> {
     Ok(None)
 }
 
+#[instrument(skip(writer, _vars))]
+fn builtin_set(
+    args: Vec<String>,
+    writer: &mut dyn Write,
+    _vars: &mut VarStore,
+    config: &mut Config,
+) -> Result<Option<ExitCode>> {
+    debug!("executable builtin command 'set'");
+
+    match args.as_slice() {
+        [] => {
+            for key in Config::KEYS {
+                let value = config.get(key).expect("key is one of Config::KEYS");
+                writeln!(writer, "{key}: {value}")?;
+            }
+        }
+        [key] => match config.get(key) {
+            Some(value) => writeln!(writer, "{key}: {value}")?,
+            None => writeln!(writer, "set: unknown config key '{key}'")?,
+        },
+        [key, value] => config.set(key, value)?,
+        _ => bail!("usage: set [key] [value]"),
+    }
+
+    Ok(None)
+}
+
 trait StrExt {
     fn trim_newline(&self) -> &Self;
 }
@@ -266,3 +862,239 @@ impl StrExt for str {
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses and runs `input` to completion, returning the status the shell
+    /// would report via `$?` afterwards.
+    fn run(input: &str) -> u8 {
+        let mut vars: VarStore = HashMap::new();
+        vars.insert("?".to_owned(), "0".to_owned());
+        let mut config = Config::default();
+
+        let mut status = 0;
+        for element in parse(input).unwrap() {
+            match run_pipeline(&element.pipeline, &mut vars, &mut config).unwrap() {
+                PipelineOutcome::Continue(s) => status = s,
+                PipelineOutcome::Exit(_) => unreachable!("no stage here calls exit"),
+            }
+        }
+
+        status
+    }
+
+    #[test]
+    fn pipeline_status_is_the_last_stage_s_even_behind_a_failing_external_stage() {
+        assert_eq!(run("true | false | echo done"), 0);
+    }
+
+    #[test]
+    fn pipeline_status_of_an_external_only_pipeline_is_the_last_stage_s() {
+        assert_eq!(run("true | false"), 1);
+        assert_eq!(run("false | true"), 0);
+    }
+
+    #[test]
+    fn pipeline_status_of_a_single_external_command_is_unaffected() {
+        assert_eq!(run("false"), 1);
+        assert_eq!(run("true"), 0);
+    }
+
+    fn expandable(s: &str) -> Word {
+        Word(vec![WordPart::Expandable(s.to_owned())])
+    }
+
+    fn literal(s: &str) -> Word {
+        Word(vec![WordPart::Literal(s.to_owned())])
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace() {
+        assert_eq!(
+            tokenize("echo  hi").unwrap(),
+            vec![Token::Word(expandable("echo")), Token::Word(expandable("hi"))]
+        );
+    }
+
+    #[test]
+    fn tokenize_single_quotes_are_literal_and_unescaped() {
+        assert_eq!(
+            tokenize(r"'a\ b'").unwrap(),
+            vec![Token::Word(literal(r"a\ b"))]
+        );
+    }
+
+    #[test]
+    fn tokenize_double_quotes_expand_but_only_escape_a_few_characters() {
+        // `\$` is one of the characters double quotes escape, so it becomes a
+        // literal `$`; `\!` isn't, so the backslash survives and `!` stays
+        // expandable.
+        assert_eq!(
+            tokenize(r#""a\$b\!c""#).unwrap(),
+            vec![Token::Word(Word(vec![
+                WordPart::Expandable("a".to_owned()),
+                WordPart::Literal("$".to_owned()),
+                WordPart::Expandable(r"b\!c".to_owned()),
+            ]))]
+        );
+    }
+
+    #[test]
+    fn tokenize_concatenates_adjacent_quoted_and_unquoted_runs_into_one_word() {
+        assert_eq!(
+            tokenize(r#"hi'there'"you""#).unwrap(),
+            vec![Token::Word(Word(vec![
+                WordPart::Expandable("hi".to_owned()),
+                WordPart::Literal("there".to_owned()),
+                WordPart::Expandable("you".to_owned()),
+            ]))]
+        );
+    }
+
+    #[test]
+    fn tokenize_rejects_an_unterminated_quote() {
+        assert!(tokenize("'unterminated").is_err());
+        assert!(tokenize("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn tokenize_recognizes_pipe_sequence_and_and_or_operators() {
+        assert_eq!(
+            tokenize("a | b ; c && d || e").unwrap(),
+            vec![
+                Token::Word(expandable("a")),
+                Token::Pipe,
+                Token::Word(expandable("b")),
+                Token::Sequence,
+                Token::Word(expandable("c")),
+                Token::And,
+                Token::Word(expandable("d")),
+                Token::Or,
+                Token::Word(expandable("e")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_redirects_default_to_stdout_and_stdin() {
+        assert_eq!(
+            tokenize("a > b").unwrap()[1],
+            Token::Redirect(RedirectSpec {
+                fd: 1,
+                mode: RedirectMode::Truncate,
+            })
+        );
+        assert_eq!(
+            tokenize("a < b").unwrap()[1],
+            Token::Redirect(RedirectSpec {
+                fd: 0,
+                mode: RedirectMode::Read,
+            })
+        );
+        assert_eq!(
+            tokenize("a >> b").unwrap()[1],
+            Token::Redirect(RedirectSpec {
+                fd: 1,
+                mode: RedirectMode::Append,
+            })
+        );
+    }
+
+    #[test]
+    fn tokenize_an_adjacent_digit_run_selects_the_redirect_s_fd() {
+        assert_eq!(
+            tokenize("a 2> b").unwrap()[1],
+            Token::Redirect(RedirectSpec {
+                fd: 2,
+                mode: RedirectMode::Truncate,
+            })
+        );
+    }
+
+    #[test]
+    fn tokenize_a_digit_run_followed_by_whitespace_is_a_plain_word_not_an_fd_prefix() {
+        assert_eq!(
+            tokenize("a 2 > b").unwrap(),
+            vec![
+                Token::Word(expandable("a")),
+                Token::Word(expandable("2")),
+                Token::Redirect(RedirectSpec {
+                    fd: 1,
+                    mode: RedirectMode::Truncate,
+                }),
+                Token::Word(expandable("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_a_quoted_digit_run_is_a_plain_word_not_an_fd_prefix() {
+        assert_eq!(
+            tokenize("a '2'> b").unwrap(),
+            vec![
+                Token::Word(expandable("a")),
+                Token::Word(literal("2")),
+                Token::Redirect(RedirectSpec {
+                    fd: 1,
+                    mode: RedirectMode::Truncate,
+                }),
+                Token::Word(expandable("b")),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"a "2"> b"#).unwrap(),
+            vec![
+                Token::Word(expandable("a")),
+                Token::Word(expandable("2")),
+                Token::Redirect(RedirectSpec {
+                    fd: 1,
+                    mode: RedirectMode::Truncate,
+                }),
+                Token::Word(expandable("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_splits_leading_name_value_words_off_as_assignments() {
+        let plan = parse("FOO=bar echo hi").unwrap();
+        let cmd = &plan[0].pipeline[0];
+        assert_eq!(cmd.assignments, vec![("FOO".to_owned(), expandable("bar"))]);
+        assert_eq!(cmd.program, Some(expandable("echo")));
+        assert_eq!(cmd.args, vec![expandable("hi")]);
+    }
+
+    #[test]
+    fn parse_attaches_redirections_to_the_stage_they_follow() {
+        let plan = parse("echo hi > out.txt 2>> err.txt").unwrap();
+        let cmd = &plan[0].pipeline[0];
+        assert_eq!(
+            cmd.redirections,
+            vec![
+                Redirection {
+                    fd: 1,
+                    mode: RedirectMode::Truncate,
+                    target: expandable("out.txt"),
+                },
+                Redirection {
+                    fd: 2,
+                    mode: RedirectMode::Append,
+                    target: expandable("err.txt"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_groups_pipeline_stages_and_sequence_separators() {
+        let plan = parse("a | b ; c && d").unwrap();
+        assert_eq!(plan[0].separator, None);
+        assert_eq!(plan[0].pipeline.len(), 2);
+        assert_eq!(plan[1].separator, Some(Separator::Sequence));
+        assert_eq!(plan[1].pipeline.len(), 1);
+        assert_eq!(plan[2].separator, Some(Separator::And));
+        assert_eq!(plan[2].pipeline.len(), 1);
+    }
+}